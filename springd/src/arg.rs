@@ -8,6 +8,7 @@ use clap::{
     Parser, ValueEnum, ValueHint,
 };
 use clap_complete::Shell;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -29,6 +30,31 @@ fn parse_duration(arg: &str) -> Result<Duration, std::num::ParseIntError> {
     Ok(Duration::from_secs(seconds))
 }
 
+fn parse_stage(s: &str) -> Result<crate::dispatcher::Stage, String> {
+    let parts: Vec<&str> = s.split(':').collect();
+    let [connections, rate, duration] = parts.as_slice() else {
+        return Err(format!(
+            "expected <connections>:<rate>:<duration>, got `{s}`"
+        ));
+    };
+
+    let connections: u16 =
+        connections.parse().map_err(|e| format!("connections: {e}"))?;
+    let rate = if *rate == "-" {
+        None
+    } else {
+        Some(rate.parse::<u16>().map_err(|e| format!("rate: {e}"))?)
+    };
+    let duration =
+        parse_duration(duration).map_err(|e| format!("duration: {e}"))?;
+
+    Ok(crate::dispatcher::Stage {
+        connections,
+        rate,
+        duration,
+    })
+}
+
 /// define supported http methods
 #[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
 pub enum Method {
@@ -91,6 +117,94 @@ impl ValueEnum for Method {
     }
 }
 
+/// which HTTP protocol version to benchmark over
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Default)]
+pub enum HttpVersion {
+    /// force HTTP/1.1
+    Http1,
+
+    /// force HTTP/2 (prior knowledge, no ALPN negotiation)
+    Http2,
+
+    /// HTTP/3 over QUIC, one QUIC connection per `--connections`, each
+    /// shared by several concurrent worker streams
+    Http3,
+
+    /// let reqwest negotiate the version via ALPN
+    #[default]
+    Auto,
+}
+
+impl IntoResettable<OsStr> for HttpVersion {
+    fn into_resettable(self) -> Resettable<OsStr> {
+        match self {
+            HttpVersion::Http1 => Value(OsStr::from("1")),
+            HttpVersion::Http2 => Value(OsStr::from("2")),
+            HttpVersion::Http3 => Value(OsStr::from("3")),
+            HttpVersion::Auto => Value(OsStr::from("auto")),
+        }
+    }
+}
+
+impl ValueEnum for HttpVersion {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            HttpVersion::Http1,
+            HttpVersion::Http2,
+            HttpVersion::Http3,
+            HttpVersion::Auto,
+        ]
+    }
+
+    fn to_possible_value<'a>(&self) -> Option<PossibleValue> {
+        Some(match self {
+            HttpVersion::Http1 => PossibleValue::new("1"),
+            HttpVersion::Http2 => PossibleValue::new("2"),
+            HttpVersion::Http3 => PossibleValue::new("3"),
+            HttpVersion::Auto => PossibleValue::new("auto"),
+        })
+    }
+}
+
+/// output format for `--report`
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Default)]
+pub enum ReportFormat {
+    /// a self-contained HTML page with latency, throughput and status-code
+    /// tables
+    #[default]
+    Html,
+
+    /// the same tables rendered as Markdown
+    Md,
+
+    /// the raw report data as JSON, for feeding into other tooling
+    Json,
+}
+
+impl IntoResettable<OsStr> for ReportFormat {
+    fn into_resettable(self) -> Resettable<OsStr> {
+        match self {
+            ReportFormat::Html => Value(OsStr::from("html")),
+            ReportFormat::Md => Value(OsStr::from("md")),
+            ReportFormat::Json => Value(OsStr::from("json")),
+        }
+    }
+}
+
+impl ValueEnum for ReportFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[ReportFormat::Html, ReportFormat::Md, ReportFormat::Json]
+    }
+
+    fn to_possible_value<'a>(&self) -> Option<PossibleValue> {
+        Some(match self {
+            ReportFormat::Html => PossibleValue::new("html"),
+            ReportFormat::Md => PossibleValue::new("md"),
+            ReportFormat::Json => PossibleValue::new("json"),
+        })
+    }
+}
+
 #[derive(Debug, Parser)]
 #[command(author, version, about, allow_missing_positional(true))]
 #[command(help_template(
@@ -125,6 +239,16 @@ pub struct Arg {
     #[arg(long, short, help = "Print latency statistics")]
     pub(crate) latencies: bool,
 
+    /// Latency percentiles to report, e.g. `0.5 0.9 0.99`
+    #[arg(
+        long,
+        num_args = 0..,
+        default_value = "0.5 0.9 0.99",
+        help = "Latency percentiles to report (can be repeated)",
+        value_delimiter = ' '
+    )]
+    pub(crate) percentiles: Vec<f32>,
+
     /// Request method
     #[arg(
         long,
@@ -180,6 +304,15 @@ pub struct Arg {
     #[arg(long, short = 'a', help = "Disable HTTP keep-alive")]
     pub(crate) disable_keep_alive: bool,
 
+    /// HTTP protocol version to benchmark: 1, 2, 3, or auto
+    #[arg(
+        long,
+        default_value = HttpVersion::Auto,
+        value_enum,
+        help = "HTTP protocol version to benchmark: 1, 2, 3, or auto"
+    )]
+    pub(crate) http_version: HttpVersion,
+
     #[arg(
         long,
         short = 'H',
@@ -195,7 +328,7 @@ pub struct Arg {
         short = 'n',
         help = "Number of requests",
         conflicts_with = "duration",
-        required_unless_present_any(["duration", "completions"])
+        required_unless_present_any(["duration", "completions", "stages"])
     )]
     pub(crate) requests: Option<u64>,
 
@@ -206,7 +339,7 @@ pub struct Arg {
         value_parser = parse_duration,
         help = "Duration of test",
         conflicts_with = "requests",
-        required_unless_present_any(["requests", "completions"])
+        required_unless_present_any(["requests", "completions", "stages"])
     )]
     pub(crate) duration: Option<Duration>,
 
@@ -214,6 +347,155 @@ pub struct Arg {
     #[arg(long, short = 'r', help = "Rate limit in requests per second")]
     pub(crate) rate: Option<u16>,
 
+    /// Burst capacity allowed above --rate, as a number of requests
+    /// (defaults to one second's worth of --rate)
+    #[arg(
+        long,
+        requires = "rate",
+        help = "Burst capacity above --rate (defaults to one second's worth)"
+    )]
+    pub(crate) burst: Option<f64>,
+
+    /// Throttle window used to pace --rate: once per window, permits for
+    /// that window (rate * quantum) are handed out in one batch instead of
+    /// every worker independently awaiting the rate limiter. Shorter
+    /// windows pace more smoothly at the cost of more frequent wakeups.
+    #[arg(
+        long,
+        default_value_t = 2,
+        help = "Throttle window length in milliseconds used to pace --rate"
+    )]
+    pub(crate) quantum: u64,
+
+    /// Staged load profile: <connections>:<rate>:<duration>, rate may be
+    /// `-` for unlimited. Repeatable; stages run in the order given and
+    /// override --connections/--rate for their duration, e.g.
+    /// `--stage 10:-:30s --stage 500:1000:60s`
+    #[arg(
+        long = "stage",
+        value_parser = parse_stage,
+        action = clap::ArgAction::Append,
+        help = "Staged load profile <connections>:<rate>:<duration> (repeatable)"
+    )]
+    pub(crate) stages: Vec<crate::dispatcher::Stage>,
+
+    /// Search for the maximum sustainable rate instead of driving a fixed
+    /// one, using an AIMD control loop bound by --slo-latency/--slo-error-rate
+    #[arg(
+        long,
+        conflicts_with = "rate",
+        requires = "duration",
+        help = "Discover the maximum sustainable rate under the SLO flags \
+                instead of using --rate"
+    )]
+    pub(crate) find_max_rate: bool,
+
+    /// p99 latency SLO (milliseconds) used by --find-max-rate
+    #[arg(
+        long,
+        default_value_t = 500,
+        requires = "find_max_rate",
+        help = "p99 latency SLO in milliseconds, used by --find-max-rate"
+    )]
+    pub(crate) slo_latency: u64,
+
+    /// error rate SLO (0.0-1.0) used by --find-max-rate
+    #[arg(
+        long,
+        default_value_t = 0.01,
+        requires = "find_max_rate",
+        help = "Error rate SLO (0.0-1.0), used by --find-max-rate"
+    )]
+    pub(crate) slo_error_rate: f64,
+
+    /// Serve live benchmark state in Prometheus text exposition format on
+    /// this address, e.g. `127.0.0.1:9090`, so a long-running test can be
+    /// scraped instead of only reporting a summary once it finishes
+    #[arg(
+        long,
+        help = "Serve Prometheus metrics for this run on <host:port>"
+    )]
+    pub(crate) metrics_addr: Option<SocketAddr>,
+
+    /// Benchmark a gRPC endpoint instead of plain HTTP, using --grpc-method
+    /// to select the call. This tool does not parse a .proto file to
+    /// encode the request, so --body must already hold the serialized
+    /// protobuf message for the call (see --proto)
+    #[arg(
+        long,
+        requires = "grpc_method",
+        help = "Benchmark a gRPC endpoint instead of plain HTTP"
+    )]
+    pub(crate) grpc: bool,
+
+    /// Path to the .proto file describing the gRPC service, for reference
+    /// only: it is not parsed or used to encode the request, so --body
+    /// must already hold the serialized protobuf message
+    #[arg(
+        long,
+        value_hint = ValueHint::FilePath,
+        requires = "grpc",
+        help = "Path to the .proto file describing the gRPC service (reference only, not parsed)"
+    )]
+    pub(crate) proto: Option<PathBuf>,
+
+    /// Full gRPC method to call, e.g. package.Service/Method
+    #[arg(
+        long,
+        requires = "grpc",
+        help = "gRPC method to call, e.g. package.Service/Method"
+    )]
+    pub(crate) grpc_method: Option<String>,
+
+    /// Number of client-streaming messages to send per call; 1 is a unary
+    /// call
+    #[arg(
+        long,
+        default_value_t = 1,
+        requires = "grpc",
+        help = "Number of client-streaming messages to send per call (1 = unary)"
+    )]
+    pub(crate) grpc_stream_messages: u32,
+
+    /// Sample the tokio runtime once per progress tick (total poll count,
+    /// busy duration, mean/max task poll times, scheduler queue depth) and
+    /// fold it into the final summary; helps tell a slow target apart
+    /// from a poll-starved, saturated load generator
+    #[arg(
+        long,
+        help = "Sample tokio runtime stats and include them in the summary"
+    )]
+    pub(crate) runtime_stats: bool,
+
+    /// Write a report of the run's statistics to this path once it
+    /// finishes, rendered through an embedded Handlebars template
+    #[arg(
+        long,
+        value_hint = ValueHint::FilePath,
+        help = "Write a report of the run to this path"
+    )]
+    pub(crate) report: Option<PathBuf>,
+
+    /// Report output format: html, md, or json
+    #[arg(
+        long,
+        default_value = ReportFormat::Html,
+        value_enum,
+        requires = "report",
+        help = "Report output format: html, md, or json"
+    )]
+    pub(crate) report_format: ReportFormat,
+
+    /// Override the bundled Handlebars template used for --report-format
+    /// html/md with one loaded from this path
+    #[arg(
+        long,
+        value_hint = ValueHint::FilePath,
+        requires = "report",
+        help = "Handlebars template overriding the bundled html/md report template"
+    )]
+    pub(crate) report_template: Option<PathBuf>,
+
     #[arg(long, value_enum)]
     pub completions: Option<Shell>,
 
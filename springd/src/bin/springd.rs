@@ -50,8 +50,33 @@ fn create_duration_progress_bar(arg: &Arg) -> ProgressBar {
     pb
 }
 
+fn create_staged_progress_bar() -> ProgressBar {
+    // a staged run has no single request count or duration to measure
+    // progress against (each stage has its own), so use a length-less
+    // spinner instead of a bar
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} [{elapsed_precise}] {msg}",
+        )
+            .unwrap()
+            .tick_strings(&[
+                "▹▹▹▹▹",
+                "▸▹▹▹▹",
+                "▹▸▹▹▹",
+                "▹▹▸▹▹",
+                "▹▹▹▸▹",
+                "▹▹▹▹▸",
+                "▪▪▪▪▪",
+            ]),
+    );
+    pb
+}
+
 fn create_progress_bar(arg: &Arg) -> ProgressBar {
-    if arg.requests.is_some() {
+    if !arg.stages.is_empty() {
+        create_staged_progress_bar()
+    } else if arg.requests.is_some() {
         create_count_progress_bar(arg)
     } else {
         create_duration_progress_bar(arg)
@@ -59,7 +84,14 @@ fn create_progress_bar(arg: &Arg) -> ProgressBar {
 }
 
 fn print_tip(arg: &Arg) {
-    if arg.requests.is_some() {
+    if !arg.stages.is_empty() {
+        println!(
+            "{:?} {:?} through {} stage(s)",
+            arg.method,
+            arg.url.clone().unwrap(),
+            arg.stages.len()
+        );
+    } else if arg.requests.is_some() {
         println!(
             "{:?} {:?} with {} requests using {} connections",
             arg.method,
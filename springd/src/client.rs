@@ -0,0 +1,209 @@
+//! client module builds the transport used to send requests: a pooled
+//! [reqwest::Client] for HTTP/1.1, HTTP/2 and ALPN-negotiated `auto`, or a
+//! pooled QUIC connection for HTTP/3, selected via `--http-version`
+
+use crate::arg::{Arg, HttpVersion};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, Identity};
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+fn build_headers(arg: &Arg) -> anyhow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for header in &arg.headers {
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(
+                HeaderName::from_bytes(name.trim().as_bytes())?,
+                HeaderValue::from_str(value.trim())?,
+            );
+        }
+    }
+    Ok(headers)
+}
+
+/// load `--cert`/`--key` into a client builder, if set; shared by
+/// [build_client] and [build_grpc_client]
+fn with_client_identity(
+    builder: reqwest::ClientBuilder,
+    arg: &Arg,
+) -> anyhow::Result<reqwest::ClientBuilder> {
+    let Some(cert) = &arg.cert else {
+        return Ok(builder);
+    };
+
+    let mut pem = fs::read(cert)?;
+    if let Some(key) = &arg.key {
+        pem.extend(fs::read(key)?);
+    }
+    Ok(builder
+        .identity(Identity::from_pem(&pem)?)
+        .add_root_certificate(Certificate::from_pem(&pem)?))
+}
+
+/// build the reqwest client used for HTTP/1.1, HTTP/2 and `auto`
+pub(crate) fn build_client(arg: &Arg) -> anyhow::Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(arg.timeout)
+        .danger_accept_invalid_certs(arg.insecure)
+        .default_headers(build_headers(arg)?);
+
+    if arg.disable_keep_alive {
+        builder = builder.pool_max_idle_per_host(0);
+    }
+
+    builder = with_client_identity(builder, arg)?;
+
+    builder = match arg.http_version {
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        HttpVersion::Http3 | HttpVersion::Auto => builder,
+    };
+
+    Ok(builder.build()?)
+}
+
+/// build the reqwest client used for `--grpc` calls; gRPC is defined over
+/// HTTP/2, so prior knowledge is always forced on regardless of
+/// `--http-version`
+pub(crate) fn build_grpc_client(arg: &Arg) -> anyhow::Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(arg.timeout)
+        .danger_accept_invalid_certs(arg.insecure)
+        .http2_prior_knowledge();
+
+    builder = with_client_identity(builder, arg)?;
+
+    Ok(builder.build()?)
+}
+
+/// a single QUIC connection opened for HTTP/3, along with the request
+/// sender used to open new streams on it; one of these is built per
+/// `--connections` and its handle is cloned to each worker that
+/// multiplexes streams over it
+pub(crate) struct QuicConnection {
+    pub(crate) send_request:
+        h3::client::SendRequest<h3_quinn::OpenStreams, bytes::Bytes>,
+}
+
+impl QuicConnection {
+    /// clone the request-sending handle so a second worker can open its
+    /// own streams on the same underlying QUIC connection
+    pub(crate) fn clone_handle(&self) -> QuicConnection {
+        QuicConnection {
+            send_request: self.send_request.clone(),
+        }
+    }
+}
+
+fn quic_client_config(insecure: bool) -> anyhow::Result<quinn::ClientConfig> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    let mut tls_config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls_config.alpn_protocols = vec![b"h3".to_vec()];
+
+    if insecure {
+        tls_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(
+                crate::client::NoVerify,
+            ));
+    }
+
+    Ok(quinn::ClientConfig::new(Arc::new(
+        quinn::crypto::rustls::QuicClientConfig::try_from(tls_config)?,
+    )))
+}
+
+struct NoVerify;
+
+impl rustls::client::danger::ServerCertVerifier for NoVerify {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error>
+    {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &rustls::pki_types::CertificateDer<'_>,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<
+        rustls::client::danger::HandshakeSignatureValid,
+        rustls::Error,
+    > {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &rustls::pki_types::CertificateDer<'_>,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<
+        rustls::client::danger::HandshakeSignatureValid,
+        rustls::Error,
+    > {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(
+        &self,
+    ) -> Vec<rustls::SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// open one QUIC connection and drive the HTTP/3 handshake on it; this is
+/// called once per `--connections`, and several workers share the result
+/// since QUIC multiplexes many streams over a single connection
+pub(crate) async fn build_quic_connection(
+    arg: &Arg,
+    url: &str,
+) -> anyhow::Result<QuicConnection> {
+    let url = url::Url::parse(url)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("target url has no host"))?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("could not resolve {host}"))?;
+
+    // bind the endpoint to the same address family as the resolved target:
+    // an IPv6-only ("[::]:0") endpoint can't dial an IPv4 peer on hosts
+    // where `bindv6only=1`
+    let bind_addr = match addr {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    };
+
+    let client_config = quic_client_config(arg.insecure)?;
+    let mut endpoint = quinn::Endpoint::client(bind_addr.parse()?)?;
+    endpoint.set_default_client_config(client_config);
+
+    let connection = endpoint.connect(addr, host)?.await?;
+    let quinn_conn = h3_quinn::Connection::new(connection);
+    let (mut driver, send_request) = h3::client::new(quinn_conn).await?;
+
+    // the connection driver must keep running for the lifetime of the
+    // connection to process incoming control frames
+    tokio::spawn(async move {
+        let _ = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+    });
+
+    Ok(QuicConnection { send_request })
+}
@@ -1,10 +1,13 @@
 //! dispatcher module is used to distribute tasks according to different models
 
 use crate::limiter::Limiter;
+use crate::statistics::Statistics;
 use async_trait::async_trait;
 use log::{debug, error, info};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering::*};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::sync::Mutex as TMutex;
 
 #[async_trait]
 pub trait Dispatcher: Send + Sync {
@@ -21,6 +24,20 @@ pub trait Dispatcher: Send + Sync {
     /// when the program receives an external termination signal, notify the
     /// Dispatcher to process it
     fn cancel(&mut self);
+
+    /// an extra line appended to the run summary; most dispatchers have
+    /// nothing to add, but e.g. [AdaptiveDispatcher] reports the rate it
+    /// discovered
+    fn summary_line(&self) -> Option<String> {
+        None
+    }
+
+    /// current target worker-pool size, if the dispatcher models a
+    /// staged/ramping concurrency; `None` means `--connections` is
+    /// authoritative for the whole run and the pool size never changes
+    fn target_concurrency(&self) -> Option<u16> {
+        None
+    }
 }
 
 /// [CountDispatcher] is a count based task dispatcher
@@ -45,20 +62,19 @@ pub struct CountDispatcher {
     limiter: Option<Limiter>,
 }
 
-fn new_limiter(rate: &Option<u16>) -> Option<Limiter> {
-    let mut limiter: Option<Limiter> = None;
-    if let Some(rate) = rate {
-        limiter = Some(Limiter::new(*rate));
-    }
-    limiter
+fn new_limiter(rate: &Option<u16>, burst: Option<f64>) -> Option<Limiter> {
+    rate.map(|rate| match burst {
+        Some(burst) => Limiter::with_burst(rate as f64, burst),
+        None => Limiter::new(rate as f64),
+    })
 }
 
 impl CountDispatcher {
-    /// give total and rat, return [Dispatcher]
-    pub fn new(total: u64, rate: &Option<u16>) -> Self {
+    /// give total and rate, return [Dispatcher]
+    pub fn new(total: u64, rate: &Option<u16>, burst: Option<f64>) -> Self {
         Self {
             total,
-            limiter: new_limiter(rate),
+            limiter: new_limiter(rate, burst),
             applied: AtomicU64::new(0),
             completed: AtomicU64::new(0),
             is_canceled: AtomicBool::new(false),
@@ -142,12 +158,16 @@ pub struct DurationDispatcher {
 }
 
 impl DurationDispatcher {
-    pub fn new(duration: Duration, rate: &Option<u16>) -> Self {
+    pub fn new(
+        duration: Duration,
+        rate: &Option<u16>,
+        burst: Option<f64>,
+    ) -> Self {
         Self {
             duration,
             canceled_at: None,
             start: Instant::now(),
-            limiter: new_limiter(rate),
+            limiter: new_limiter(rate, burst),
             total: AtomicU64::new(0),
             is_canceled: AtomicBool::new(false),
             is_done: AtomicBool::new(false),
@@ -209,3 +229,386 @@ impl Dispatcher for DurationDispatcher {
         }
     }
 }
+
+/// number of past control intervals considered when judging convergence
+const CONVERGENCE_WINDOW: usize = 5;
+
+/// factor the allowed rate is cut by when feedback exceeds an SLO
+const MULTIPLICATIVE_DECREASE: f64 = 0.8;
+
+/// [AdaptiveDispatcher] is a closed-loop dispatcher: rather than driving a
+/// fixed rate, it runs an AIMD control loop that additively raises the
+/// allowed rate while the target stays within its latency/error SLOs and
+/// multiplicatively cuts it the moment feedback from [Statistics] shows it
+/// doesn't, converging on the maximum rate the target can sustain.
+pub struct AdaptiveDispatcher {
+    /// number of requests executed so far
+    total: AtomicU64,
+
+    /// start time for executing the search
+    start: Instant,
+
+    /// total duration to run the search for
+    duration: Duration,
+
+    /// rate limiter driven by the current allowed rate `R`
+    limiter: Limiter,
+
+    /// current allowed rate `R`, adjusted once per control interval
+    rate: Mutex<f64>,
+
+    /// rate recorded at the end of each control interval, used to judge
+    /// convergence via variance over [CONVERGENCE_WINDOW] intervals
+    rate_history: Mutex<Vec<f64>>,
+
+    /// time the last control-interval adjustment ran
+    last_tick: Mutex<Instant>,
+
+    /// statistics used as feedback for the control loop
+    statistics: Arc<Statistics>,
+
+    /// length of a control interval
+    control_interval: Duration,
+
+    /// how long to wait before the first adjustment, so statistics have a
+    /// chance to accumulate under the initial rate
+    warmup: Duration,
+
+    /// additive increase step, in requests/sec
+    step: f64,
+
+    /// rate floor so the search never stalls at zero
+    min_rate: f64,
+
+    /// p99 latency SLO; feedback above this triggers a multiplicative cut
+    slo_latency: Duration,
+
+    /// error rate SLO; feedback above this triggers a multiplicative cut
+    slo_error_rate: f64,
+
+    /// indicates whether it is canceled
+    is_canceled: AtomicBool,
+
+    /// indicate whether to complete
+    is_done: AtomicBool,
+}
+
+impl AdaptiveDispatcher {
+    /// construct an [AdaptiveDispatcher] that searches for the maximum
+    /// sustainable rate within `duration`, starting from `initial_rate`
+    /// requests/sec and never dropping below `min_rate`
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        duration: Duration,
+        statistics: Arc<Statistics>,
+        initial_rate: f64,
+        min_rate: f64,
+        step: f64,
+        control_interval: Duration,
+        warmup: Duration,
+        slo_latency: Duration,
+        slo_error_rate: f64,
+    ) -> Self {
+        Self {
+            duration,
+            statistics,
+            control_interval,
+            warmup,
+            step,
+            min_rate,
+            slo_latency,
+            slo_error_rate,
+            start: Instant::now(),
+            last_tick: Mutex::new(Instant::now()),
+            limiter: Limiter::new(initial_rate),
+            rate: Mutex::new(initial_rate),
+            rate_history: Mutex::new(Vec::new()),
+            total: AtomicU64::new(0),
+            is_canceled: AtomicBool::new(false),
+            is_done: AtomicBool::new(false),
+        }
+    }
+
+    /// run one control-loop adjustment if the warmup has elapsed and the
+    /// previous interval has fully passed; additively bumps the rate when
+    /// the target is within its SLOs, otherwise multiplicatively cuts it
+    async fn tick(&self) {
+        if Instant::now() - self.start < self.warmup {
+            return;
+        }
+
+        {
+            let mut last_tick = self.last_tick.lock().unwrap();
+            if Instant::now() - *last_tick < self.control_interval {
+                return;
+            }
+            *last_tick = Instant::now();
+        }
+
+        let (error_rate, p99) = self.statistics.live_snapshot().await;
+        let rate = {
+            let mut rate = self.rate.lock().unwrap();
+            if error_rate > self.slo_error_rate || p99 > self.slo_latency {
+                *rate = (*rate * MULTIPLICATIVE_DECREASE).max(self.min_rate);
+            } else {
+                *rate += self.step;
+            }
+            *rate
+        };
+        self.limiter.set_rate(rate).await;
+
+        let mut history = self.rate_history.lock().unwrap();
+        history.push(rate);
+        if history.len() > CONVERGENCE_WINDOW {
+            history.remove(0);
+        }
+    }
+
+    /// variance of the last [CONVERGENCE_WINDOW] adjusted rates; a small
+    /// variance relative to the rate means the search has converged
+    fn rate_variance(&self) -> Option<f64> {
+        let history = self.rate_history.lock().unwrap();
+        if history.len() < CONVERGENCE_WINDOW {
+            return None;
+        }
+        let mean = history.iter().sum::<f64>() / history.len() as f64;
+        let variance = history.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+            / history.len() as f64;
+        Some(variance)
+    }
+}
+
+#[async_trait]
+impl Dispatcher for AdaptiveDispatcher {
+    fn get_process(&self) -> f64 {
+        if self.is_done.load(Acquire) {
+            return 1.0;
+        }
+
+        // converged searches report done early rather than waiting out the
+        // rest of the configured duration
+        if let Some(variance) = self.rate_variance() {
+            let rate = *self.rate.lock().unwrap();
+            if rate > 0.0 && variance.sqrt() / rate < 0.02 {
+                return 1.0;
+            }
+        }
+
+        let run_time = Instant::now() - self.start;
+        run_time.as_secs() as f64 / self.duration.as_secs() as f64
+    }
+
+    async fn try_apply_job(&self) -> bool {
+        if self.is_done.load(Acquire) || self.is_canceled.load(Acquire) {
+            return false;
+        }
+
+        self.tick().await;
+        self.limiter.allow().await;
+
+        if Instant::now() - self.start >= self.duration {
+            return false;
+        }
+
+        self.total.fetch_add(1, SeqCst);
+        true
+    }
+
+    fn complete_job(&self) {
+        if Instant::now() - self.start >= self.duration
+            && !self.is_done.load(Acquire)
+        {
+            self.is_done.store(true, SeqCst);
+        }
+    }
+
+    fn cancel(&mut self) {
+        if !self.is_canceled.load(Acquire) {
+            self.is_canceled.store(true, SeqCst);
+        }
+    }
+
+    fn summary_line(&self) -> Option<String> {
+        let rate = *self.rate.lock().unwrap();
+        Some(format!("discovered sustainable rate: {rate:.1} req/s"))
+    }
+}
+
+/// one phase of a [StagedDispatcher] load profile: hold `connections`
+/// concurrent workers driven at `rate` (or unlimited) for `duration`
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub connections: u16,
+    pub rate: Option<u16>,
+    pub duration: Duration,
+}
+
+/// [StagedDispatcher] drives a sequence of [Stage]s instead of one fixed
+/// concurrency/rate for the whole run, so a test can ramp up, hold, and
+/// spike. The worker-pool supervisor in [crate::task::Task::run] reads
+/// [Dispatcher::target_concurrency] to grow or shrink the live pool as
+/// stages change.
+pub struct StagedDispatcher {
+    /// the stages to run through, in order
+    stages: Vec<Stage>,
+
+    /// time the current stage started
+    stage_start: Mutex<Instant>,
+
+    /// index into `stages` of the stage currently running
+    current_stage: AtomicU64,
+
+    /// rate limiter for the current stage; `None` means unlimited
+    limiter: TMutex<Option<Limiter>>,
+
+    /// number of requests applied for across all stages
+    total: AtomicU64,
+
+    /// number of requests completed across all stages; `total - completed`
+    /// approximates the number of workers currently in flight, which is
+    /// how [StagedDispatcher::try_apply_job] decides a worker is surplus
+    /// to the current stage's target concurrency
+    completed: AtomicU64,
+
+    /// indicates whether it is canceled
+    is_canceled: AtomicBool,
+
+    /// indicate whether to complete
+    is_done: AtomicBool,
+}
+
+impl StagedDispatcher {
+    /// construct a [StagedDispatcher] that runs through `stages` in order
+    pub fn new(stages: Vec<Stage>) -> Self {
+        let limiter = stages
+            .first()
+            .and_then(|s| s.rate)
+            .map(|rate| Limiter::new(rate as f64));
+        Self {
+            stages,
+            stage_start: Mutex::new(Instant::now()),
+            current_stage: AtomicU64::new(0),
+            limiter: TMutex::new(limiter),
+            total: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            is_canceled: AtomicBool::new(false),
+            is_done: AtomicBool::new(false),
+        }
+    }
+
+    fn current(&self) -> Option<&Stage> {
+        self.stages.get(self.current_stage.load(Acquire) as usize)
+    }
+
+    /// move on to the next stage once the current one's duration has
+    /// elapsed, swapping in its rate limiter
+    async fn advance_if_elapsed(&self) {
+        let current_index = self.current_stage.load(Acquire);
+        let Some(stage) = self.stages.get(current_index as usize) else {
+            return;
+        };
+        if Instant::now() - *self.stage_start.lock().unwrap() < stage.duration {
+            return;
+        }
+
+        // hundreds of workers can observe the elapsed duration in the same
+        // instant; serialize the advance on the limiter's mutex so exactly
+        // one of them moves `current_stage` forward, instead of each
+        // passing the check above and the stage jumping by more than one
+        let mut limiter = self.limiter.lock().await;
+        if self.current_stage.load(Acquire) != current_index {
+            // another worker already advanced past this boundary
+            return;
+        }
+        if Instant::now() - *self.stage_start.lock().unwrap() < stage.duration {
+            return;
+        }
+
+        let next_index = current_index + 1;
+        self.current_stage.store(next_index, SeqCst);
+        *self.stage_start.lock().unwrap() = Instant::now();
+        *limiter = self
+            .stages
+            .get(next_index as usize)
+            .and_then(|s| s.rate)
+            .map(|rate| Limiter::new(rate as f64));
+    }
+}
+
+#[async_trait]
+impl Dispatcher for StagedDispatcher {
+    fn get_process(&self) -> f64 {
+        // a canceled run should be reported done immediately: workers stop
+        // applying for jobs as soon as `is_canceled` is set, and `join_workers`
+        // (the staged path in `task.rs::Task::run`) waits on this value, so
+        // reporting the unchanged wall-clock fraction here would block
+        // Ctrl-C for roughly the rest of the schedule
+        if self.is_done.load(Acquire) || self.is_canceled.load(Acquire) {
+            return 1.0;
+        }
+
+        let total_duration: Duration =
+            self.stages.iter().map(|s| s.duration).sum();
+        if total_duration.is_zero() {
+            return 1.0;
+        }
+
+        let current_index = self.current_stage.load(Acquire) as usize;
+        let completed_duration: Duration = self
+            .stages
+            .get(..current_index.min(self.stages.len()))
+            .unwrap_or(&[])
+            .iter()
+            .map(|s| s.duration)
+            .sum();
+        let stage_elapsed = Instant::now() - *self.stage_start.lock().unwrap();
+
+        ((completed_duration + stage_elapsed).as_secs_f64()
+            / total_duration.as_secs_f64())
+        .min(1.0)
+    }
+
+    async fn try_apply_job(&self) -> bool {
+        if self.is_done.load(Acquire) || self.is_canceled.load(Acquire) {
+            return false;
+        }
+
+        self.advance_if_elapsed().await;
+
+        let Some(stage) = self.current() else {
+            self.is_done.store(true, SeqCst);
+            return false;
+        };
+
+        // a worker beyond the current stage's target concurrency is
+        // surplus: reject it so it exits, shrinking the live pool down to
+        // the new target instead of holding onto oversized concurrency
+        // from an earlier, larger stage
+        let in_flight =
+            self.total.load(Acquire).saturating_sub(self.completed.load(Acquire));
+        if in_flight >= stage.connections as u64 {
+            return false;
+        }
+
+        if let Some(limiter) = &*self.limiter.lock().await {
+            limiter.allow().await;
+        }
+
+        self.total.fetch_add(1, SeqCst);
+        true
+    }
+
+    fn complete_job(&self) {
+        self.completed.fetch_add(1, SeqCst);
+    }
+
+    fn cancel(&mut self) {
+        if !self.is_canceled.load(Acquire) {
+            self.is_canceled.store(true, SeqCst);
+        }
+    }
+
+    fn target_concurrency(&self) -> Option<u16> {
+        self.current().map(|s| s.connections)
+    }
+}
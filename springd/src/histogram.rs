@@ -0,0 +1,159 @@
+//! mod histogram implements a bounded-memory, log-bucketed latency
+//! histogram, used in place of a growing `Vec<Duration>` of samples so
+//! that tracking latency quantiles costs constant memory regardless of
+//! how many requests are sent
+
+use std::time::Duration;
+
+/// number of buckets spanning [MIN_NANOS, MAX_NANOS]; higher means more
+/// precise quantiles at the cost of a bigger fixed-size histogram
+const BUCKET_COUNT: usize = 256;
+
+/// latencies below this are folded into the first bucket
+const MIN_NANOS: f64 = 1_000.0; // 1us
+
+/// latencies above this are folded into the last bucket
+const MAX_NANOS: f64 = 60_000_000_000.0; // 60s
+
+/// a streaming, HDR-style latency histogram: each observed [Duration] is
+/// folded into one of [BUCKET_COUNT] logarithmically-spaced buckets
+/// covering [MIN_NANOS, MAX_NANOS] nanoseconds, so memory is fixed no
+/// matter how many samples are recorded. Quantiles are computed by
+/// walking buckets low-to-high, accumulating counts until the running
+/// total reaches `quantile * total`; the reported latency is then that
+/// bucket's upper bound, accurate to within the bucket's width rather
+/// than exact.
+#[derive(Debug, Clone)]
+pub(crate) struct LatencyHistogram {
+    buckets: [u64; BUCKET_COUNT],
+    total: u64,
+}
+
+impl LatencyHistogram {
+    /// construct an empty histogram
+    pub(crate) fn new() -> Self {
+        Self {
+            buckets: [0; BUCKET_COUNT],
+            total: 0,
+        }
+    }
+
+    /// fold a latency sample into its bucket
+    pub(crate) fn record(&mut self, latency: Duration) {
+        self.buckets[Self::bucket_of(latency)] += 1;
+        self.total += 1;
+    }
+
+    /// total number of samples recorded
+    pub(crate) fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// reset to empty, keeping the allocation
+    pub(crate) fn clear(&mut self) {
+        self.buckets.fill(0);
+        self.total = 0;
+    }
+
+    /// fold another histogram's buckets into this one
+    pub(crate) fn merge(&mut self, other: &Self) {
+        for (bucket, other_bucket) in
+            self.buckets.iter_mut().zip(other.buckets.iter())
+        {
+            *bucket += other_bucket;
+        }
+        self.total += other.total;
+    }
+
+    /// the approximate `quantile` latency (0.0..=1.0), walking buckets
+    /// until the running count reaches `quantile * total`
+    pub(crate) fn quantile(&self, quantile: f32) -> Duration {
+        if self.total == 0 {
+            return Duration::from_secs(0);
+        }
+
+        let target = ((self.total as f64) * (quantile as f64)).ceil() as u64;
+        let target = target.max(1);
+
+        let mut cumulative = 0u64;
+        for (idx, count) in self.buckets.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= target {
+                return Self::bucket_upper_bound(idx);
+            }
+        }
+        Self::bucket_upper_bound(BUCKET_COUNT - 1)
+    }
+
+    /// approximate mean latency, weighting each bucket's midpoint by its
+    /// count
+    pub(crate) fn mean(&self) -> Duration {
+        if self.total == 0 {
+            return Duration::from_secs(0);
+        }
+
+        let weighted: f64 = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(idx, count)| Self::bucket_midpoint_nanos(idx) * *count as f64)
+            .sum();
+        Duration::from_nanos((weighted / self.total as f64) as u64)
+    }
+
+    /// approximate maximum latency: the upper bound of the highest
+    /// non-empty bucket
+    pub(crate) fn max(&self) -> Duration {
+        match self.buckets.iter().rposition(|count| *count > 0) {
+            Some(idx) => Self::bucket_upper_bound(idx),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    /// approximate standard deviation, from bucket midpoints weighted by
+    /// count around [LatencyHistogram::mean]
+    pub(crate) fn stdev(&self) -> Duration {
+        if self.total == 0 {
+            return Duration::from_secs(0);
+        }
+
+        let mean_nanos = self.mean().as_nanos() as f64;
+        let variance: f64 = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(idx, count)| {
+                let diff = Self::bucket_midpoint_nanos(idx) - mean_nanos;
+                diff * diff * *count as f64
+            })
+            .sum::<f64>()
+            / self.total as f64;
+        Duration::from_nanos(variance.sqrt() as u64)
+    }
+
+    fn bucket_of(latency: Duration) -> usize {
+        let nanos = (latency.as_nanos() as f64).clamp(MIN_NANOS, MAX_NANOS);
+        let step = (nanos / MIN_NANOS).ln() / (MAX_NANOS / MIN_NANOS).ln();
+        let idx = (step * (BUCKET_COUNT - 1) as f64) as usize;
+        idx.min(BUCKET_COUNT - 1)
+    }
+
+    fn bucket_upper_bound(idx: usize) -> Duration {
+        Duration::from_nanos(Self::bucket_edge_nanos(idx + 1) as u64)
+    }
+
+    fn bucket_midpoint_nanos(idx: usize) -> f64 {
+        (Self::bucket_edge_nanos(idx) + Self::bucket_edge_nanos(idx + 1)) / 2.0
+    }
+
+    fn bucket_edge_nanos(idx: usize) -> f64 {
+        let step = idx as f64 / (BUCKET_COUNT - 1) as f64;
+        MIN_NANOS * (MAX_NANOS / MIN_NANOS).powf(step)
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -6,8 +6,12 @@
 pub mod arg;
 pub(crate) mod client;
 pub(crate) mod dispatcher;
+pub(crate) mod histogram;
 pub(crate) mod limiter;
+pub(crate) mod metrics;
+pub(crate) mod report;
 pub(crate) mod request;
+pub(crate) mod scheduler;
 pub(crate) mod statistics;
 pub mod task;
 
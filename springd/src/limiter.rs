@@ -1,35 +1,83 @@
-use governor::{
-    clock::DefaultClock,
-    state::{direct::NotKeyed, InMemoryState},
-    Quota, RateLimiter,
-};
-use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 use tokio::time;
 
-/// Limiter limit only sending a fixed number of requests per second
+struct State {
+    /// tokens currently available to spend
+    tokens: f64,
+
+    /// burst allowance: the maximum number of tokens that can accumulate
+    capacity: f64,
+
+    /// tokens added per second
+    refill_per_sec: f64,
+
+    /// last time `tokens` was topped up
+    last_refill: Instant,
+}
+
+/// Limiter is a token-bucket rate limiter: it allows `refill_per_sec`
+/// requests/sec on average, but lets short bursts above that rate through
+/// up to `capacity` tokens before it starts making callers wait
 pub(crate) struct Limiter {
-    inner: RateLimiter<NotKeyed, InMemoryState, DefaultClock>,
+    state: Mutex<State>,
 }
 
 impl Limiter {
-    /// create a new Limiter
-    pub fn new(rate: u16) -> Limiter {
+    /// create a new Limiter with a one second burst allowance
+    pub fn new(rate: f64) -> Limiter {
+        Self::with_burst(rate, rate)
+    }
+
+    /// create a new Limiter with a configurable burst allowance
+    pub fn with_burst(rate: f64, burst: f64) -> Limiter {
+        let refill_per_sec = rate.max(f64::MIN_POSITIVE);
+        let capacity = burst.max(refill_per_sec);
         Self {
-            inner: RateLimiter::direct(Quota::per_second(
-                NonZeroU32::new(rate as u32).unwrap(),
-            )),
+            state: Mutex::new(State {
+                tokens: capacity,
+                capacity,
+                refill_per_sec,
+                last_refill: Instant::now(),
+            }),
         }
     }
 
     /// allow function return means that the next action can be performed,
-    /// otherwise wait here
+    /// otherwise wait here until a token is available
     pub(crate) async fn allow(&self) {
         loop {
-            let result = self.inner.check();
-            if result.is_ok() {
-                break;
+            let wait = {
+                let mut state = self.state.lock().await;
+                let elapsed = state.last_refill.elapsed();
+                state.tokens = (state.tokens
+                    + elapsed.as_secs_f64() * state.refill_per_sec)
+                    .min(state.capacity);
+                state.last_refill = Instant::now();
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    None
+                } else {
+                    Some(Duration::from_secs_f64(
+                        (1.0 - state.tokens) / state.refill_per_sec,
+                    ))
+                }
+            };
+
+            match wait {
+                None => return,
+                Some(wait) => time::sleep(wait).await,
             }
-            time::sleep(time::Duration::from_nanos(100)).await;
         }
     }
+
+    /// replace the current rate at runtime, e.g. when a closed-loop
+    /// controller adjusts its allowed rate between control intervals;
+    /// the burst capacity grows to match if the new rate exceeds it
+    pub(crate) async fn set_rate(&self, rate: f64) {
+        let mut state = self.state.lock().await;
+        state.refill_per_sec = rate.max(f64::MIN_POSITIVE);
+        state.capacity = state.capacity.max(state.refill_per_sec);
+    }
 }
@@ -0,0 +1,89 @@
+//! metrics module serves the current benchmark state in Prometheus text
+//! exposition format over `--metrics-addr`, so a long `--duration` run can
+//! be scraped by an existing Prometheus/Grafana stack instead of only
+//! reporting once the run finishes
+
+use crate::statistics::{MetricsSnapshot, Statistics};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use log::error;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// render a [MetricsSnapshot] as Prometheus text exposition format
+fn render(snapshot: MetricsSnapshot) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP springd_requests_total Total requests sent.\n");
+    out.push_str("# TYPE springd_requests_total counter\n");
+    out.push_str(&format!("springd_requests_total {}\n", snapshot.total));
+
+    out.push_str(
+        "# HELP springd_responses_total Responses by status-code class.\n",
+    );
+    out.push_str("# TYPE springd_responses_total counter\n");
+    for (class, count) in snapshot.by_class {
+        out.push_str(&format!(
+            "springd_responses_total{{class=\"{class}\"}} {count}\n"
+        ));
+    }
+
+    out.push_str(
+        "# HELP springd_bytes_read_total Total response bytes read.\n",
+    );
+    out.push_str("# TYPE springd_bytes_read_total counter\n");
+    out.push_str(&format!(
+        "springd_bytes_read_total {}\n",
+        snapshot.bytes_read
+    ));
+
+    out.push_str(
+        "# HELP springd_requests_per_second Responses received so far in the \
+         current statistics tick (resets every tick).\n",
+    );
+    out.push_str("# TYPE springd_requests_per_second gauge\n");
+    out.push_str(&format!(
+        "springd_requests_per_second {}\n",
+        snapshot.current_req_per_second
+    ));
+
+    out.push_str(
+        "# HELP springd_latency_seconds Latency quantiles observed so far.\n",
+    );
+    out.push_str("# TYPE springd_latency_seconds summary\n");
+    for (quantile, latency) in snapshot.latency_quantiles {
+        out.push_str(&format!(
+            "springd_latency_seconds{{quantile=\"{quantile}\"}} {}\n",
+            latency.as_secs_f64()
+        ));
+    }
+
+    out
+}
+
+async fn handle(
+    statistics: Arc<Statistics>,
+    _req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let snapshot = statistics.metrics_snapshot().await;
+    Ok(Response::new(Body::from(render(snapshot))))
+}
+
+/// run the Prometheus metrics server for the lifetime of the process; this
+/// is spawned alongside the benchmark [crate::task::Task] and is never
+/// joined, since it has no natural stop point before the run itself ends
+pub(crate) async fn serve(addr: SocketAddr, statistics: Arc<Statistics>) {
+    let make_svc = make_service_fn(move |_conn| {
+        let statistics = statistics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(statistics.clone(), req)
+            }))
+        }
+    });
+
+    if let Err(err) = Server::bind(&addr).serve(make_svc).await {
+        error!("metrics server failed: {err:?}");
+    }
+}
@@ -0,0 +1,78 @@
+//! report module renders the aggregated [crate::statistics::Statistics]
+//! results into a `--report` file once a run finishes, following the
+//! pattern of hyper-based services that embed Handlebars templates for
+//! their generated pages: default templates are bundled into the binary
+//! via `include_str!`, and `--report-template` overrides them with one
+//! loaded from disk
+
+use crate::arg::ReportFormat;
+use crate::statistics::{ReportSnapshot, Statistics};
+use crate::Arg;
+use handlebars::{
+    Context, Handlebars, Helper, HelperResult, Output, RenderContext,
+    RenderError,
+};
+use std::fs;
+
+const DEFAULT_HTML_TEMPLATE: &str =
+    include_str!("../templates/report.html.hbs");
+const DEFAULT_MD_TEMPLATE: &str = include_str!("../templates/report.md.hbs");
+
+/// Handlebars helper rendering a serialized [std::time::Duration] (a
+/// `{secs, nanos}` object) as seconds with millisecond precision, e.g.
+/// `{{fmt_duration latency}}` -> `"1.234s"`. Splicing `secs` and `nanos`
+/// directly in a template mis-formats sub-second durations (no padding),
+/// so templates must go through this helper rather than the raw fields.
+fn fmt_duration_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = h
+        .param(0)
+        .map(|p| p.value())
+        .ok_or_else(|| RenderError::new("fmt_duration: missing argument"))?;
+    let secs = value.get("secs").and_then(|v| v.as_u64()).unwrap_or(0);
+    let nanos = value.get("nanos").and_then(|v| v.as_u64()).unwrap_or(0);
+    let seconds = secs as f64 + nanos as f64 / 1_000_000_000.0;
+    out.write(&format!("{seconds:.3}s"))?;
+    Ok(())
+}
+
+/// render a [ReportSnapshot] as the format requested by `--report-format`
+fn render(arg: &Arg, snapshot: &ReportSnapshot) -> anyhow::Result<String> {
+    if arg.report_format == ReportFormat::Json {
+        return Ok(serde_json::to_string_pretty(snapshot)?);
+    }
+
+    let template = match &arg.report_template {
+        Some(path) => fs::read_to_string(path)?,
+        None => match arg.report_format {
+            ReportFormat::Html => DEFAULT_HTML_TEMPLATE.to_string(),
+            ReportFormat::Md => DEFAULT_MD_TEMPLATE.to_string(),
+            ReportFormat::Json => unreachable!(),
+        },
+    };
+
+    let mut handlebars = Handlebars::new();
+    handlebars.register_helper("fmt_duration", Box::new(fmt_duration_helper));
+    Ok(handlebars.render_template(&template, snapshot)?)
+}
+
+/// write the `--report` file for this run; a no-op if `--report` wasn't
+/// given. Must be called after [Statistics::summary] has run
+pub(crate) async fn write_report(
+    arg: &Arg,
+    statistics: &Statistics,
+) -> anyhow::Result<()> {
+    let Some(path) = &arg.report else {
+        return Ok(());
+    };
+
+    let snapshot = statistics.report_data().await;
+    let rendered = render(arg, &snapshot)?;
+    fs::write(path, rendered)?;
+    Ok(())
+}
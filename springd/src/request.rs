@@ -1,6 +1,7 @@
+use crate::client::QuicConnection;
 use crate::Arg;
 use bytes::Bytes;
-use reqwest::{multipart, Body, Client, Request, RequestBuilder};
+use reqwest::{multipart, Body, Client, Request, RequestBuilder, StatusCode};
 use std::collections::HashMap;
 use tokio::{self, fs as tfs};
 use tokio_util::codec::{BytesCodec, FramedRead};
@@ -27,6 +28,101 @@ pub(crate) async fn build_request(
     }
 }
 
+/// send one request over an already-established QUIC connection,
+/// mirroring [build_request]/[Client::execute] for the HTTP/3 path
+pub(crate) async fn send_h3_request(
+    arg: &Arg,
+    connection: &mut QuicConnection,
+) -> anyhow::Result<StatusCode> {
+    let req = http::Request::builder()
+        .method(arg.method.to_reqwest_method().as_str())
+        .uri(arg.url.as_ref().unwrap().clone())
+        .body(())?;
+
+    let mut stream = connection.send_request.send_request(req).await?;
+    if let Some(body) = &arg.body {
+        stream.send_data(Bytes::from(body.clone())).await?;
+    }
+    stream.finish().await?;
+
+    let response = stream.recv_response().await?;
+    Ok(response.status())
+}
+
+/// frame a single protobuf message using gRPC's length-prefixed wire
+/// format: a 1-byte compression flag (always 0, uncompressed) followed by
+/// a 4-byte big-endian length and the raw payload
+fn frame_grpc_message(payload: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(5 + payload.len());
+    framed.push(0u8);
+    framed.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    framed.extend_from_slice(payload);
+    framed
+}
+
+/// map a `grpc-status` trailer value onto the [StatusCode] the shared
+/// statistics pipeline classifies by; `0` is `OK`, anything else is
+/// reported as a server error so it is counted as a failure
+fn grpc_status_to_http(status: u32) -> StatusCode {
+    if status == 0 {
+        StatusCode::OK
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// send one gRPC call to `--grpc-method`: a single message for a unary
+/// call, or `--grpc-stream-messages` messages framed and concatenated into
+/// one HTTP/2 request body for client-streaming. `--body` is sent as-is as
+/// the message payload: this tool does not parse `--proto` to encode it,
+/// so `--body` must already hold the serialized protobuf message.
+///
+/// `Response::trailers()` only returns HTTP/2 trailers on a reqwest build
+/// new enough to expose them (0.11.14+); on an older pin this always
+/// resolves to `None` and every call falls back to classifying by HTTP
+/// status, same as a transport error with no `grpc-status` trailer
+pub(crate) async fn send_grpc_request(
+    arg: &Arg,
+    client: &Client,
+) -> anyhow::Result<StatusCode> {
+    let method = arg
+        .grpc_method
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("--grpc-method is required with --grpc"))?;
+    let base = arg.url.as_ref().unwrap().trim_end_matches('/');
+    let url = format!("{base}/{method}");
+
+    let payload = arg.body.as_deref().unwrap_or_default();
+    let mut body = Vec::new();
+    for _ in 0..arg.grpc_stream_messages.max(1) {
+        body.extend_from_slice(&frame_grpc_message(payload.as_bytes()));
+    }
+
+    let mut response = client
+        .post(url)
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(body)
+        .send()
+        .await?;
+
+    // capture the HTTP status before consuming the body for trailers, so a
+    // response with no `grpc-status` trailer (e.g. a proxy/transport error
+    // that never reaches the gRPC server) isn't silently counted as OK
+    let http_status = response.status();
+
+    let grpc_status = response
+        .trailers()
+        .await?
+        .and_then(|trailers| trailers.get("grpc-status").cloned())
+        .and_then(|value| value.to_str().ok().and_then(|s| s.parse::<u32>().ok()));
+
+    Ok(match grpc_status {
+        Some(status) => grpc_status_to_http(status),
+        None => http_status,
+    })
+}
+
 async fn set_request_text_body(
     arg: &Arg,
     mut builder: RequestBuilder,
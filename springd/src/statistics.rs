@@ -1,7 +1,8 @@
 //! mod statistics counts all relevant information about the server response
 
-use num::integer::Roots;
+use crate::histogram::LatencyHistogram;
 use reqwest::{Error, Response, StatusCode};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering::*};
 use std::time::{Duration, Instant};
@@ -64,8 +65,23 @@ pub(crate) struct Statistics {
     /// stdev per request, link: https://en.wikipedia.org/wiki/Standard_deviation
     stdev_req_elapsed_time: tsync::Mutex<Duration>,
 
-    /// used internally to record the time spent on each request
-    elapsed_time: tsync::Mutex<Vec<Duration>>,
+    /// bounded-memory latency histogram covering the whole run, used to
+    /// derive [Statistics::avg_req_elapsed_time], [Statistics::max_req_elapsed_time]
+    /// and the final percentiles instead of keeping every sample
+    latency_histogram: tsync::Mutex<LatencyHistogram>,
+
+    /// latency histogram for the current tick only; snapshotted into
+    /// [Statistics::latency_series] and reset every tick by
+    /// [Statistics::timer_per_second]
+    tick_histogram: tsync::Mutex<LatencyHistogram>,
+
+    /// number of failed requests seen since the last tick, paired with
+    /// `current_cumulative`'s success count
+    current_cumulative_errors: AtomicU64,
+
+    /// per-tick time series of request count, error count and latency
+    /// quantiles, one entry per [Statistics::timer_per_second] tick
+    latency_series: tsync::Mutex<Vec<TickLatency>>,
 
     /// indicates whether to stop, used to notify the internal timer to exit
     is_stopped: AtomicBool,
@@ -78,6 +94,14 @@ pub(crate) struct Statistics {
 
     /// latencies for different percentiles
     latencies: tsync::Mutex<Vec<(f32, Duration)>>,
+
+    /// total response bytes read, derived from each response's
+    /// `Content-Length` header
+    bytes_read: AtomicU64,
+
+    /// most recent tokio runtime sample, taken under `--runtime-stats`;
+    /// `None` unless that flag is set
+    runtime_stats: tsync::Mutex<Option<RuntimeStatsSnapshot>>,
 }
 
 impl Statistics {
@@ -103,10 +127,15 @@ impl Statistics {
             stopped_at: tsync::Mutex::new(None),
             latencies: tsync::Mutex::new(Vec::new()),
             throughput: tsync::Mutex::new(0.0),
-            elapsed_time: tsync::Mutex::new(Vec::new()),
+            latency_histogram: tsync::Mutex::new(LatencyHistogram::new()),
+            tick_histogram: tsync::Mutex::new(LatencyHistogram::new()),
+            current_cumulative_errors: AtomicU64::new(0),
+            latency_series: tsync::Mutex::new(Vec::new()),
             avg_req_elapsed_time: tsync::Mutex::new(Duration::from_secs(0)),
             max_req_elapsed_time: tsync::Mutex::new(Duration::from_secs(0)),
             stdev_req_elapsed_time: tsync::Mutex::new(Duration::from_secs(0)),
+            bytes_read: AtomicU64::new(0),
+            runtime_stats: tsync::Mutex::new(None),
         }
     }
 
@@ -123,8 +152,9 @@ impl Statistics {
     }
 
     /// used to start the internal timer, and generate a box of snapshots for
-    /// some data every second
-    pub(crate) async fn timer_per_second(&self) {
+    /// some data every two seconds; `percentiles` are the quantiles sampled into
+    /// each [TickLatency] pushed onto [Statistics::latency_series]
+    pub(crate) async fn timer_per_second(&self, percentiles: Vec<f32>) {
         let mut timer = ttime::interval(Duration::from_secs(2));
         loop {
             timer.tick().await;
@@ -133,6 +163,22 @@ impl Statistics {
                 req_per_second.push(self.current_cumulative.load(Acquire));
                 self.current_cumulative.store(0, SeqCst);
             }
+            {
+                let mut tick_histogram = self.tick_histogram.lock().await;
+                let tick_latency = TickLatency {
+                    count: tick_histogram.count(),
+                    error_count: self.current_cumulative_errors.swap(0, SeqCst),
+                    quantiles: percentiles
+                        .iter()
+                        .map(|percentile| PercentileRow {
+                            percentile: *percentile,
+                            latency: tick_histogram.quantile(*percentile),
+                        })
+                        .collect(),
+                };
+                tick_histogram.clear();
+                self.latency_series.lock().await.push(tick_latency);
+            }
             if self.is_stopped.load(Acquire) {
                 break;
             }
@@ -178,16 +224,16 @@ impl Statistics {
         }
     }
 
-    async fn handle_resp_error(&self, err: Error) {
-        let err_msg = format!("{err}");
+    async fn handle_resp_error(&self, err_msg: String, status: Option<StatusCode>) {
         let mut errors = self.errors.lock().await;
         errors
             .entry(err_msg)
             .and_modify(|count| *count += 1)
             .or_insert(1);
-        if let Some(status) = err.status() {
+        if let Some(status) = status {
             self.statistics_rsp_code(status);
         }
+        self.current_cumulative_errors.fetch_add(1, SeqCst);
     }
 
     /// receive message and make statistics
@@ -195,23 +241,37 @@ impl Statistics {
         let Message {
             rsp_at,
             req_at,
-            response,
+            outcome,
         } = message;
 
         self.total.fetch_add(1, SeqCst);
 
-        if response.is_err() {
-            let err = response.err().unwrap();
-            self.handle_resp_error(err).await;
-            return;
-        }
+        let (status, bytes) = match outcome {
+            Outcome::Status(status, bytes) => (status, bytes),
+            Outcome::Error(err_msg, status) => {
+                self.handle_resp_error(err_msg, status).await;
+                return;
+            },
+        };
 
-        let response = response.unwrap();
-        self.statistics_rsp_code(response.status());
+        self.statistics_rsp_code(status);
+        self.bytes_read.fetch_add(bytes, SeqCst);
         self.total_success.fetch_add(1, SeqCst);
         self.current_cumulative.fetch_add(1, SeqCst);
-        let mut elapsed_time = self.elapsed_time.lock().await;
-        elapsed_time.push(rsp_at - req_at);
+        let latency = rsp_at - req_at;
+        self.latency_histogram.lock().await.record(latency);
+        self.tick_histogram.lock().await.record(latency);
+    }
+
+    /// record the latest tokio runtime sample under `--runtime-stats`; it
+    /// replaces the previous sample and is folded into the final summary
+    /// via [Statistics]'s `Debug` output
+    pub(crate) async fn record_runtime_stats(
+        &self,
+        snapshot: RuntimeStatsSnapshot,
+    ) {
+        let mut runtime_stats = self.runtime_stats.lock().await;
+        *runtime_stats = Some(snapshot);
     }
 
     /// notify stop timer
@@ -243,39 +303,20 @@ impl Statistics {
     }
 
     async fn calculate_elapsed_time(&self) {
-        let mut elapsed_time = self.elapsed_time.lock().await;
-        if (*elapsed_time).is_empty() {
+        let latency_histogram = self.latency_histogram.lock().await;
+        if latency_histogram.count() == 0 {
             return;
         }
-        elapsed_time.sort();
 
-        // avg_req_elapsed_time
         let mut avg_req_elapsed_time = self.avg_req_elapsed_time.lock().await;
-        let total: Duration = elapsed_time.iter().sum();
-        let count = elapsed_time.len();
-        *avg_req_elapsed_time = total / count as u32;
+        *avg_req_elapsed_time = latency_histogram.mean();
 
-        // max_req_elapsed_time
         let mut max_req_elapsed_time = self.max_req_elapsed_time.lock().await;
-        if let Some(max) = elapsed_time.iter().max() {
-            *max_req_elapsed_time = *max;
-        }
+        *max_req_elapsed_time = latency_histogram.max();
 
-        // stdev_req_elapsed_time
-        let sum = (*elapsed_time).iter().sum::<Duration>();
-        let mean = (sum as Duration / count as u32).as_nanos();
-        let variance: u128 = (*elapsed_time)
-            .iter()
-            .map(|x| {
-                let diff: i128 = (*x).as_nanos() as i128 - mean as i128;
-                (diff * diff) as u128
-            })
-            .sum::<u128>()
-            / count as u128;
-        let stdev = variance.sqrt();
         let mut stdev_req_elapsed_time =
             self.stdev_req_elapsed_time.lock().await;
-        *stdev_req_elapsed_time = Duration::from_nanos(stdev as u64);
+        *stdev_req_elapsed_time = latency_histogram.stdev();
     }
 
     async fn calculate_stdev_per_second(&self) {
@@ -317,32 +358,107 @@ impl Statistics {
     }
 
     async fn calculate_latencies(&self, percentiles: Vec<f32>) {
-        let mut elapsed_time = self.elapsed_time.lock().await;
-        if elapsed_time.is_empty() {
+        let latency_histogram = self.latency_histogram.lock().await;
+        if latency_histogram.count() == 0 {
             return;
         }
-        if !elapsed_time.is_sorted() {
-            elapsed_time.sort();
-        }
 
         let mut latencies = self.latencies.lock().await;
-        let count = elapsed_time.len();
         for percent in percentiles {
-            let percent_len = (count as f32 * percent) as usize;
-            if percent_len > count {
-                continue;
-            }
-            let percent_elapsed_time: &[Duration] =
-                &(*elapsed_time)[..percent_len];
-            let sum = percent_elapsed_time.iter().sum::<Duration>();
-            latencies.push((percent, sum / percent_len as u32));
+            latencies.push((percent, latency_histogram.quantile(percent)));
+        }
+    }
+
+    /// take a cheap, in-progress read of the *recent* error rate and p99
+    /// latency (since the last [Statistics::timer_per_second] tick, which
+    /// runs every two seconds, not the whole run), without
+    /// disturbing the samples collected for the final summary; used by
+    /// controllers (e.g. [crate::dispatcher::AdaptiveDispatcher]) that need
+    /// feedback that tracks the target's current behavior. Feeding an AIMD
+    /// loop from lifetime totals instead would let early good samples mask
+    /// later degradation (it would keep additively increasing) and, once
+    /// enough failures accumulate, keep the cumulative error rate above the
+    /// SLO forever, so it could never recover
+    pub(crate) async fn live_snapshot(&self) -> (f64, Duration) {
+        let tick_histogram = self.tick_histogram.lock().await;
+        let errors = self.current_cumulative_errors.load(Acquire);
+        let total = tick_histogram.count() + errors;
+        if total == 0 {
+            return (0.0, Duration::from_secs(0));
+        }
+
+        let error_rate = errors as f64 / total as f64;
+        let p99 = tick_histogram.quantile(0.99);
+        (error_rate, p99)
+    }
+
+    /// cheap, in-progress snapshot used by [crate::metrics]'s Prometheus
+    /// endpoint; unlike [Statistics::summary] it doesn't mutate or clear
+    /// any of the samples collected for the final report
+    pub(crate) async fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let latency_histogram = self.latency_histogram.lock().await;
+
+        MetricsSnapshot {
+            total: self.total.load(Acquire),
+            by_class: [
+                ("1xx", self.rsp1xx.load(Acquire)),
+                ("2xx", self.rsp2xx.load(Acquire)),
+                ("3xx", self.rsp3xx.load(Acquire)),
+                ("4xx", self.rsp4xx.load(Acquire)),
+                ("5xx", self.rsp5xx.load(Acquire)),
+                ("other", self.rsp_others.load(Acquire)),
+            ],
+            bytes_read: self.bytes_read.load(Acquire),
+            current_req_per_second: self.current_cumulative.load(Acquire),
+            latency_quantiles: [
+                (0.5, latency_histogram.quantile(0.5)),
+                (0.9, latency_histogram.quantile(0.9)),
+                (0.99, latency_histogram.quantile(0.99)),
+            ],
         }
     }
 
-    async fn clear_temporary_data(&self) {
-        let mut elapsed_time = self.elapsed_time.lock().await;
-        elapsed_time.clear();
-        elapsed_time.shrink_to(0);
+    /// build the data [crate::report] renders into `--report`; must be
+    /// called after [Statistics::summary] so the aggregated fields it
+    /// reads are already populated
+    pub(crate) async fn report_data(&self) -> ReportSnapshot {
+        let started_at = self.started_at.lock().await;
+        let stopped_at = self.stopped_at.lock().await;
+        let duration = stopped_at.map(|s| s - *started_at).unwrap_or_default();
+
+        ReportSnapshot {
+            total: self.total.load(Acquire),
+            total_success: self.total_success.load(Acquire),
+            duration,
+            by_class: vec![
+                ClassCount { class: "1xx", count: self.rsp1xx.load(Acquire) },
+                ClassCount { class: "2xx", count: self.rsp2xx.load(Acquire) },
+                ClassCount { class: "3xx", count: self.rsp3xx.load(Acquire) },
+                ClassCount { class: "4xx", count: self.rsp4xx.load(Acquire) },
+                ClassCount { class: "5xx", count: self.rsp5xx.load(Acquire) },
+                ClassCount {
+                    class: "other",
+                    count: self.rsp_others.load(Acquire),
+                },
+            ],
+            bytes_read: self.bytes_read.load(Acquire),
+            avg_req_per_second: *self.avg_req_per_second.lock().await,
+            max_req_per_second: *self.max_req_per_second.lock().await,
+            req_per_second: self.req_per_second.lock().await.clone(),
+            avg_req_elapsed_time: *self.avg_req_elapsed_time.lock().await,
+            max_req_elapsed_time: *self.max_req_elapsed_time.lock().await,
+            percentiles: self
+                .latencies
+                .lock()
+                .await
+                .iter()
+                .map(|(percentile, latency)| PercentileRow {
+                    percentile: *percentile,
+                    latency: *latency,
+                })
+                .collect(),
+            latency_series: self.latency_series.lock().await.clone(),
+        }
     }
 
     /// need to manually call this method for statistical summary
@@ -357,7 +473,6 @@ impl Statistics {
         self.calculate_stdev_per_second().await;
         self.calculate_throughput(connections).await;
         self.calculate_latencies(percentiles).await;
-        self.clear_temporary_data().await;
     }
 }
 
@@ -367,24 +482,145 @@ impl Default for Statistics {
     }
 }
 
+/// a tokio runtime sample taken under `--runtime-stats`, used to tell a
+/// slow target apart from a poll-starved, saturated load generator
+#[derive(Debug, Clone)]
+pub(crate) struct RuntimeStatsSnapshot {
+    /// total number of task polls the runtime has executed
+    pub(crate) total_polls: u64,
+
+    /// total time worker threads spent busy executing tasks
+    pub(crate) total_busy_duration: Duration,
+
+    /// mean time spent per task poll
+    pub(crate) mean_poll_duration: Duration,
+
+    /// longest observed task poll
+    pub(crate) max_poll_duration: Duration,
+
+    /// number of tasks waiting in the runtime's global injection queue
+    pub(crate) injection_queue_depth: usize,
+}
+
+/// a point-in-time read of [Statistics], shaped for [crate::metrics]'s
+/// Prometheus text exposition rather than the terminal summary
+pub(crate) struct MetricsSnapshot {
+    /// total requests sent so far, success or failure
+    pub(crate) total: u64,
+
+    /// responses grouped by status-code class, e.g. `("2xx", 1234)`
+    pub(crate) by_class: [(&'static str, u64); 6],
+
+    /// total response bytes read so far
+    pub(crate) bytes_read: u64,
+
+    /// responses received so far in the current, unfinished
+    /// [Statistics::timer_per_second] tick; it resets to zero at every tick
+    /// and is a raw count, not divided by the tick length
+    pub(crate) current_req_per_second: u64,
+
+    /// latency quantiles observed so far, e.g. `(0.99, Duration)`
+    pub(crate) latency_quantiles: [(f32, Duration); 3],
+}
+
+/// a single status-code class and its count, as rendered in [ReportSnapshot]
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ClassCount {
+    pub(crate) class: &'static str,
+    pub(crate) count: u64,
+}
+
+/// a single latency percentile row, as rendered in [ReportSnapshot]
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PercentileRow {
+    pub(crate) percentile: f32,
+    pub(crate) latency: Duration,
+}
+
+/// one [Statistics::timer_per_second] tick's worth of request count, error
+/// count and latency quantiles, as rendered in [ReportSnapshot]
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct TickLatency {
+    pub(crate) count: u64,
+    pub(crate) error_count: u64,
+    pub(crate) quantiles: Vec<PercentileRow>,
+}
+
+/// the full data behind `--report`, built by [Statistics::report_data]
+/// after [Statistics::summary] has run; shared by the Handlebars
+/// (html/md) and JSON report formats in [crate::report]
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ReportSnapshot {
+    pub(crate) total: u64,
+    pub(crate) total_success: u64,
+    pub(crate) duration: Duration,
+    pub(crate) by_class: Vec<ClassCount>,
+    pub(crate) bytes_read: u64,
+    pub(crate) avg_req_per_second: f64,
+    pub(crate) max_req_per_second: f64,
+    pub(crate) req_per_second: Vec<u64>,
+    pub(crate) avg_req_elapsed_time: Duration,
+    pub(crate) max_req_elapsed_time: Duration,
+    pub(crate) percentiles: Vec<PercentileRow>,
+    pub(crate) latency_series: Vec<TickLatency>,
+}
+
+/// the result of a single request, abstracted over the transport it was
+/// sent over so both the reqwest (HTTP/1.1, HTTP/2) path and the QUIC
+/// (HTTP/3) path can report through the same [Statistics] pipeline
+pub(crate) enum Outcome {
+    /// a response was received with this status code, along with its
+    /// `Content-Length` in bytes (0 if the transport can't report one)
+    Status(StatusCode, u64),
+
+    /// the request failed before a status code was available; carries a
+    /// status code too when the transport can still recover one (e.g.
+    /// reqwest exposes the status on some error kinds)
+    Error(String, Option<StatusCode>),
+}
+
+impl From<Result<Response, Error>> for Outcome {
+    fn from(result: Result<Response, Error>) -> Self {
+        match result {
+            Ok(response) => {
+                let bytes = response.content_length().unwrap_or(0);
+                Outcome::Status(response.status(), bytes)
+            },
+            Err(err) => {
+                let status = err.status();
+                Outcome::Error(format!("{err}"), status)
+            },
+        }
+    }
+}
+
+impl From<anyhow::Result<StatusCode>> for Outcome {
+    fn from(result: anyhow::Result<StatusCode>) -> Self {
+        match result {
+            Ok(status) => Outcome::Status(status, 0),
+            Err(err) => Outcome::Error(format!("{err}"), None),
+        }
+    }
+}
+
 /// Message entity for [Statistics]
 pub struct Message {
     rsp_at: Instant,
     req_at: Instant,
-    response: Result<Response, Error>,
+    outcome: Outcome,
 }
 
 impl Message {
-    /// construct message
+    /// construct message from a transport-specific outcome
     pub fn new(
-        response: Result<Response, Error>,
+        outcome: impl Into<Outcome>,
         req_at: Instant,
         rsp_at: Instant,
     ) -> Message {
         Self {
             rsp_at,
             req_at,
-            response,
+            outcome: outcome.into(),
         }
     }
 }
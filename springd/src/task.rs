@@ -1,8 +1,14 @@
-use crate::client::build_client;
+use crate::arg::HttpVersion;
+use crate::client::{
+    build_client, build_grpc_client, build_quic_connection, QuicConnection,
+};
+use crate::dispatcher::AdaptiveDispatcher;
 use crate::dispatcher::DurationDispatcher;
+use crate::dispatcher::StagedDispatcher;
 use crate::dispatcher::{CountDispatcher, Dispatcher};
-use crate::request::build_request;
-use crate::statistics::{Message, Statistics};
+use crate::request::{build_request, send_grpc_request, send_h3_request};
+use crate::scheduler::ThrottleScheduler;
+use crate::statistics::{Message, RuntimeStatsSnapshot, Statistics};
 use crate::Arg;
 use indicatif::ProgressBar;
 use log::error;
@@ -16,45 +22,134 @@ use tokio::{
     self, runtime,
     sync::{self as tsync, mpsc},
 };
+use tokio_metrics::RuntimeMonitor;
 
 pub struct Task {
     arg: Arg,
     client: Client,
-    statistics: Statistics,
+    statistics: Arc<Statistics>,
     workers_done: AtomicBool,
     progress_bar: Option<ProgressBar>,
     dispatcher: Arc<tsync::RwLock<Box<dyn Dispatcher>>>,
+
+    /// number of worker tasks currently alive; used by the supervisor loop
+    /// to grow or shrink the live pool toward the dispatcher's current
+    /// [Dispatcher::target_concurrency] for staged load profiles
+    active_workers: std::sync::atomic::AtomicU64,
+
+    /// paces admission for a fixed `--rate` in quantum-sized batches,
+    /// instead of every worker independently awaiting the dispatcher's
+    /// rate limiter while holding `dispatcher`'s lock (workers still take
+    /// that lock for reading once to apply for and once to complete each
+    /// job); `None` for
+    /// unlimited runs and for staged/adaptive dispatchers, which already
+    /// pace themselves
+    scheduler: Option<Arc<ThrottleScheduler>>,
 }
 
 fn create_count_dispatcher(
     total: u64,
     rate: &Option<u16>,
+    burst: Option<f64>,
 ) -> Box<dyn Dispatcher> {
-    let count_dispatcher = CountDispatcher::new(total, rate);
+    let count_dispatcher = CountDispatcher::new(total, rate, burst);
     Box::new(count_dispatcher)
 }
 
 fn create_duration_dispatcher(
     duration: Duration,
     rate: &Option<u16>,
+    burst: Option<f64>,
 ) -> Box<dyn Dispatcher> {
-    let duration_dispatcher = DurationDispatcher::new(duration, rate);
+    let duration_dispatcher = DurationDispatcher::new(duration, rate, burst);
     Box::new(duration_dispatcher)
 }
 
-fn create_dispatcher(arg: &Arg) -> Arc<tsync::RwLock<Box<dyn Dispatcher>>> {
-    let dispatcher = if arg.requests.is_some() {
-        Arc::new(tsync::RwLock::new(create_count_dispatcher(
-            arg.requests.unwrap(),
-            &arg.rate,
-        )))
+/// control interval for the [AdaptiveDispatcher] AIMD loop
+const ADAPTIVE_CONTROL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// how long the [AdaptiveDispatcher] lets the target warm up before it
+/// starts adjusting the rate
+const ADAPTIVE_WARMUP: Duration = Duration::from_secs(3);
+
+/// starting rate for the [AdaptiveDispatcher] search
+const ADAPTIVE_INITIAL_RATE: f64 = 50.0;
+
+/// additive increase step for the [AdaptiveDispatcher] search
+const ADAPTIVE_STEP: f64 = 25.0;
+
+/// rate floor for the [AdaptiveDispatcher] search
+const ADAPTIVE_MIN_RATE: f64 = 1.0;
+
+/// number of concurrent HTTP/3 request streams multiplexed over each QUIC
+/// connection opened when `--http-version 3` is selected
+const STREAMS_PER_QUIC_CONNECTION: u16 = 10;
+
+fn create_adaptive_dispatcher(
+    duration: Duration,
+    statistics: Arc<Statistics>,
+    arg: &Arg,
+) -> Box<dyn Dispatcher> {
+    Box::new(AdaptiveDispatcher::new(
+        duration,
+        statistics,
+        ADAPTIVE_INITIAL_RATE,
+        ADAPTIVE_MIN_RATE,
+        ADAPTIVE_STEP,
+        ADAPTIVE_CONTROL_INTERVAL,
+        ADAPTIVE_WARMUP,
+        Duration::from_millis(arg.slo_latency),
+        arg.slo_error_rate,
+    ))
+}
+
+fn create_staged_dispatcher(arg: &Arg) -> Box<dyn Dispatcher> {
+    Box::new(StagedDispatcher::new(arg.stages.clone()))
+}
+
+fn create_dispatcher(
+    arg: &Arg,
+    statistics: Arc<Statistics>,
+) -> (
+    Arc<tsync::RwLock<Box<dyn Dispatcher>>>,
+    Option<Arc<ThrottleScheduler>>,
+) {
+    if !arg.stages.is_empty() {
+        // a staged profile swaps rate limiters per-stage itself, so it
+        // cannot be paced by one fixed-rate scheduler
+        (
+            Arc::new(tsync::RwLock::new(create_staged_dispatcher(arg))),
+            None,
+        )
+    } else if arg.find_max_rate {
+        // the AIMD search drives its own limiter's rate every control
+        // interval, so it cannot be paced by a fixed-rate scheduler either
+        (
+            Arc::new(tsync::RwLock::new(create_adaptive_dispatcher(
+                arg.duration.unwrap(),
+                statistics,
+                arg,
+            ))),
+            None,
+        )
     } else {
-        Arc::new(tsync::RwLock::new(create_duration_dispatcher(
-            arg.duration.unwrap(),
-            &arg.rate,
-        )))
-    };
-    dispatcher
+        // a fixed --rate (and its --burst allowance) is paced by the
+        // scheduler instead of the dispatcher's own limiter, so build the
+        // dispatcher unthrottled
+        let scheduler = arg.rate.map(|rate| {
+            ThrottleScheduler::new(
+                rate as f64,
+                Duration::from_millis(arg.quantum),
+                arg.burst,
+            )
+        });
+        let dispatcher = if arg.requests.is_some() {
+            create_count_dispatcher(arg.requests.unwrap(), &None, None)
+        } else {
+            create_duration_dispatcher(arg.duration.unwrap(), &None, None)
+        };
+        (Arc::new(tsync::RwLock::new(dispatcher)), scheduler)
+    }
 }
 
 impl Task {
@@ -63,16 +158,23 @@ impl Task {
         arg: Arg,
         progress_bar: Option<ProgressBar>,
     ) -> anyhow::Result<Self> {
-        let client = build_client(&arg)?;
-        let dispatcher = create_dispatcher(&arg);
+        let client = if arg.grpc {
+            build_grpc_client(&arg)?
+        } else {
+            build_client(&arg)?
+        };
+        let statistics = Arc::new(Statistics::new());
+        let (dispatcher, scheduler) = create_dispatcher(&arg, statistics.clone());
 
         Ok(Self {
             arg,
             client,
             dispatcher,
             progress_bar,
-            statistics: Statistics::new(),
+            statistics,
+            scheduler,
             workers_done: AtomicBool::new(false),
+            active_workers: std::sync::atomic::AtomicU64::new(0),
         })
     }
 
@@ -117,6 +219,32 @@ impl Task {
         }
     }
 
+    /// samples the tokio runtime once per progress tick under
+    /// `--runtime-stats`, so users can tell a slow target apart from a
+    /// poll-starved, saturated load generator
+    async fn sample_runtime_stats(self: Arc<Self>) {
+        let monitor = RuntimeMonitor::new(&runtime::Handle::current());
+        let mut intervals = monitor.intervals();
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            ticker.tick().await;
+            if let Some(metrics) = intervals.next() {
+                self.statistics
+                    .record_runtime_stats(RuntimeStatsSnapshot {
+                        total_polls: metrics.total_polls_count,
+                        total_busy_duration: metrics.total_busy_duration,
+                        mean_poll_duration: metrics.mean_poll_duration(),
+                        max_poll_duration: metrics.max_poll_duration,
+                        injection_queue_depth: metrics.injection_queue_depth,
+                    })
+                    .await;
+            }
+            if self.workers_done.load(Ordering::Acquire) {
+                break;
+            }
+        }
+    }
+
     fn finish_progress_bar(self: Arc<Self>) {
         if let Some(progress_bar) = &self.progress_bar {
             if !progress_bar.is_finished() {
@@ -126,7 +254,11 @@ impl Task {
     }
 
     async fn worker(self: Arc<Self>, sender: mpsc::Sender<Message>) {
+        self.active_workers.fetch_add(1, Ordering::SeqCst);
         loop {
+            if let Some(scheduler) = &self.scheduler {
+                scheduler.acquire().await;
+            }
             if !self.dispatcher.read().await.try_apply_job().await {
                 break;
             }
@@ -142,22 +274,90 @@ impl Task {
             let message = Message::new(response, req_at, Instant::now());
             let _ = sender.send(message).await;
         }
+        self.active_workers.fetch_sub(1, Ordering::SeqCst);
     }
 
-    async fn rcv_worker_message(
+    /// like [Task::worker], but sends requests as HTTP/3 streams over an
+    /// already-established QUIC connection instead of through `self.client`
+    async fn quic_worker(
         self: Arc<Self>,
-        mut receiver: mpsc::Receiver<Message>,
+        mut connection: QuicConnection,
+        sender: mpsc::Sender<Message>,
     ) {
+        self.active_workers.fetch_add(1, Ordering::SeqCst);
+        loop {
+            if let Some(scheduler) = &self.scheduler {
+                scheduler.acquire().await;
+            }
+            if !self.dispatcher.read().await.try_apply_job().await {
+                break;
+            }
+
+            let req_at = Instant::now();
+            let outcome = send_h3_request(&self.arg, &mut connection).await;
+            self.dispatcher.read().await.complete_job();
+            let message = Message::new(outcome, req_at, Instant::now());
+            let _ = sender.send(message).await;
+        }
+        self.active_workers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// like [Task::worker], but sends gRPC calls built by
+    /// [send_grpc_request] instead of plain HTTP requests
+    async fn grpc_worker(self: Arc<Self>, sender: mpsc::Sender<Message>) {
+        self.active_workers.fetch_add(1, Ordering::SeqCst);
         loop {
-            let result = receiver.try_recv();
-            if result.is_ok() {
-                self.statistics.handle_message(result.unwrap()).await;
-                continue;
+            if let Some(scheduler) = &self.scheduler {
+                scheduler.acquire().await;
             }
+            if !self.dispatcher.read().await.try_apply_job().await {
+                break;
+            }
+
+            let req_at = Instant::now();
+            let outcome = send_grpc_request(&self.arg, &self.client).await;
+            self.dispatcher.read().await.complete_job();
+            let message = Message::new(outcome, req_at, Instant::now());
+            let _ = sender.send(message).await;
+        }
+        self.active_workers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// periodically compares the dispatcher's current target concurrency
+    /// (set by staged load profiles) against the live worker-pool size and
+    /// spawns additional workers to grow toward it; shrinking happens
+    /// implicitly as surplus workers see `try_apply_job` return false and
+    /// exit on their own
+    async fn supervise_pool(self: Arc<Self>, sender: mpsc::Sender<Message>) {
+        loop {
             if self.workers_done.load(Ordering::Acquire) {
                 break;
             }
-            tokio::time::sleep(Duration::from_nanos(100)).await;
+
+            let target = self.dispatcher.read().await.target_concurrency();
+            let Some(target) = target else {
+                break;
+            };
+
+            let active = self.active_workers.load(Ordering::Acquire);
+            for _ in active..target as u64 {
+                tokio::spawn(self.clone().worker(sender.clone()));
+            }
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    /// drains worker messages as they arrive; this blocks on the channel
+    /// itself rather than polling, and returns once every [mpsc::Sender]
+    /// clone (held by the workers and, for staged profiles, the
+    /// supervisor) has been dropped and the channel is closed
+    async fn rcv_worker_message(
+        self: Arc<Self>,
+        mut receiver: mpsc::Receiver<Message>,
+    ) {
+        while let Some(message) = receiver.recv().await {
+            self.statistics.handle_message(message).await;
         }
     }
 
@@ -192,29 +392,146 @@ impl Task {
             let update_pb_job =
                 tokio::spawn(self.clone().update_progress_bar());
 
-            // start all worker and send request
-            for _ in 0..self.arg.connections {
-                jobs.push(tokio::spawn(self.clone().worker(tx.clone())));
+            // sample tokio runtime stats, if requested
+            let runtime_stats_job = self
+                .arg
+                .runtime_stats
+                .then(|| tokio::spawn(self.clone().sample_runtime_stats()));
+
+            // drive the quantum ticker for a fixed --rate, if one is set
+            let scheduler_job = self
+                .scheduler
+                .clone()
+                .map(|scheduler| tokio::spawn(scheduler.run()));
+
+            // serve live Prometheus metrics, if requested; this has no
+            // natural stop point before the process exits, so it's spawned
+            // and never joined, like the QUIC connection driver
+            if let Some(metrics_addr) = self.arg.metrics_addr {
+                tokio::spawn(crate::metrics::serve(
+                    metrics_addr,
+                    self.statistics.clone(),
+                ));
             }
 
+            // start all worker and send request
+            let staged = self.dispatcher.read().await.target_concurrency().is_some();
+            let supervisor_job = if staged {
+                // a staged load profile owns its own concurrency; the
+                // supervisor grows the live pool from 0 toward each
+                // stage's target, and surplus workers shrink it back down
+                // by exiting on their own when `try_apply_job` rejects them
+                Some(tokio::spawn(self.clone().supervise_pool(tx.clone())))
+            } else if self.arg.grpc {
+                for _ in 0..self.arg.connections {
+                    jobs.push(tokio::spawn(self.clone().grpc_worker(tx.clone())));
+                }
+                None
+            } else if self.arg.http_version == HttpVersion::Http3 {
+                // for HTTP/3, `connections` is the number of QUIC
+                // connections opened, each multiplexing
+                // STREAMS_PER_QUIC_CONNECTION concurrent request streams
+                for _ in 0..self.arg.connections {
+                    let url = self.arg.url.clone().unwrap();
+                    match build_quic_connection(&self.arg, &url).await {
+                        Ok(connection) => {
+                            for _ in 0..STREAMS_PER_QUIC_CONNECTION {
+                                jobs.push(tokio::spawn(
+                                    self.clone().quic_worker(
+                                        connection.clone_handle(),
+                                        tx.clone(),
+                                    ),
+                                ));
+                            }
+                        },
+                        Err(err) => error!("quic connect failed: {err:?}"),
+                    }
+                }
+                None
+            } else {
+                for _ in 0..self.arg.connections {
+                    jobs.push(tokio::spawn(self.clone().worker(tx.clone())));
+                }
+                None
+            };
+
+            // the workers and supervisor above only ever clone `tx`; drop
+            // the original so the channel closes once every worker has
+            // exited, letting `rcv_worker_message` await the channel
+            // instead of polling `workers_done`
+            drop(tx);
+
             // start statistics timer
             let task = self.clone();
+            let percentiles = self.arg.percentiles.clone();
             let stat_timer = tokio::spawn(async move {
-                task.statistics.timer_per_second().await;
+                task.statistics.timer_per_second(percentiles).await;
             });
 
-            // wait all jobs end
-            for worker in jobs {
-                let result = worker.await;
-                if result.is_err() {
-                    error!(
-                        "worker execute request failed: {:?}",
-                        result.unwrap_err()
-                    );
+            // wait all jobs end, racing against Ctrl-C so an interrupt still
+            // yields a statistics summary over whatever was measured so far
+            // instead of losing the run entirely
+            let join_workers = async {
+                if staged {
+                    // a staged profile spawns its workers from
+                    // `supervise_pool`, never pushing them into `jobs`, so
+                    // an empty `jobs` Vec would resolve this future on its
+                    // very first poll, racing ahead of the supervisor's
+                    // first tick and ending the run before a single worker
+                    // is spawned; wait for the schedule itself to finish
+                    while self.dispatcher.read().await.get_process() < 1.0 {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                } else {
+                    for worker in jobs {
+                        if let Err(err) = worker.await {
+                            error!("worker execute request failed: {err:?}");
+                        }
+                    }
+                }
+            };
+            tokio::pin!(join_workers);
+
+            let mut interrupted = false;
+            loop {
+                tokio::select! {
+                    _ = &mut join_workers => break,
+                    ctrl_c = tokio::signal::ctrl_c() => {
+                        if ctrl_c.is_err() {
+                            continue;
+                        }
+                        if interrupted {
+                            error!("second Ctrl-C received, aborting immediately");
+                            std::process::exit(130);
+                        }
+                        interrupted = true;
+                        error!(
+                            "Ctrl-C received, draining in-flight requests \
+                             (press Ctrl-C again to force quit)"
+                        );
+                        self.dispatcher.write().await.cancel();
+                    }
                 }
             }
+
+            // a staged profile can keep spawning workers after the initial
+            // batch above, so wait for the live pool to fully drain too
+            while self.active_workers.load(Ordering::Acquire) > 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
             self.workers_done.store(true, Ordering::SeqCst);
 
+            if let Some(supervisor_job) = supervisor_job {
+                let _ = supervisor_job.await;
+            }
+
+            if let Some(scheduler) = &self.scheduler {
+                scheduler.stop();
+            }
+            if let Some(scheduler_job) = scheduler_job {
+                let _ = scheduler_job.await;
+            }
+
             // notify stop statics timer
             let task = self.clone();
             tokio::spawn(async move {
@@ -229,6 +546,11 @@ impl Task {
             // wait update progress bar job finish
             update_pb_job.await.expect("update progress bar job failed");
 
+            // wait runtime stats job finish
+            if let Some(runtime_stats_job) = runtime_stats_job {
+                runtime_stats_job.await.expect("runtime stats job failed");
+            }
+
             // wait statistics timer end
             stat_timer.await.expect("statistics timer tun failed");
 
@@ -245,6 +567,14 @@ impl Task {
             .await
             .expect("statistics summary failed");
 
+            if let Some(summary_line) = self.dispatcher.read().await.summary_line() {
+                println!("{summary_line}");
+            }
+
+            if let Err(e) = crate::report::write_report(&self.arg, &self.statistics).await {
+                error!("failed to write report: {e:#}");
+            }
+
             error!("{:#?}", self.statistics);
         });
 